@@ -1,10 +1,18 @@
+use cpal::traits::DeviceTrait;
+use cpal::traits::HostTrait;
+use cpal::traits::StreamTrait;
+use cpal::Stream;
 use glam::Vec2;
 use glam::Vec3;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 //
-const AUDIO_PACKET_SIZE: usize = 131072;
+pub(crate) const AUDIO_PACKET_SIZE: usize = 131072;
 
 //
 const AUDIO_DEVICE_FREQUENCY: i32 = 44_100;
@@ -12,6 +20,29 @@ const AUDIO_DEVICE_FREQUENCY: i32 = 44_100;
 //
 const AUDIO_DEVICE_CHANNELS: u8 = 2;
 
+// Speed of sound in world-units/sec, used by `Source::doppler_ratio`.
+// This default approximates air at room temperature.
+const DEFAULT_SPEED_OF_SOUND: f32 = 343.0f32;
+
+// Clamps `doppler_ratio` to avoid runaway pitch-shifting on fast-moving
+// or numerically degenerate (near the speed of sound) sources.
+const DOPPLER_RATIO_MIN: f32 = 0.5f32;
+const DOPPLER_RATIO_MAX: f32 = 2.0f32;
+
+/// The curve `Source::volume` uses to roll off gain between
+/// `distance_min` and `distance_max`, matching the OpenAL
+/// distance-model family. `Linear` is a reasonable default for most
+/// sounds; `Inverse`/`Exponential` better approximate the sharper
+/// near-field falloff of physically loud sources (e.g. gunshots)
+/// versus a gentler ambience curve.
+#[derive(Clone, Copy, Debug)]
+pub enum DistanceModel
+{
+  Linear,
+  Inverse { rolloff: f32 },
+  Exponential { rolloff: f32 },
+}
+
 #[derive(Clone, Debug)]
 struct Source1D
 {
@@ -21,25 +52,27 @@ struct Source1D
 #[derive(Clone, Debug)]
 struct Source2D
 {
-  origin:        Vec2,
-  velocity:      Vec2,
-  volume_min:    f32,
-  volume_max:    f32,
-  distance_min:  f32,
-  distance_max:  f32,
-  sample_offset: usize,
+  origin:         Vec2,
+  velocity:       Vec2,
+  volume_min:     f32,
+  volume_max:     f32,
+  distance_min:   f32,
+  distance_max:   f32,
+  distance_model: DistanceModel,
+  sample_offset:  usize,
 }
 
 #[derive(Clone, Debug)]
 struct Source3D
 {
-  origin:        Vec3,
-  velocity:      Vec3,
-  volume_min:    f32,
-  volume_max:    f32,
-  distance_min:  f32,
-  distance_max:  f32,
-  sample_offset: usize,
+  origin:         Vec3,
+  velocity:       Vec3,
+  volume_min:     f32,
+  volume_max:     f32,
+  distance_min:   f32,
+  distance_max:   f32,
+  distance_model: DistanceModel,
+  sample_offset:  usize,
 }
 
 #[derive(Clone, Debug)]
@@ -53,8 +86,10 @@ enum SourceInternal
 #[derive(Clone, Debug)]
 pub struct Source
 {
-  sample:   usize,
-  internal: SourceInternal,
+  sample:          usize,
+  internal:        SourceInternal,
+  speed_of_sound:  f32,
+  doppler_enabled: bool,
 }
 
 impl Source
@@ -66,6 +101,8 @@ impl Source
       internal: SourceInternal::Source1D(Source1D {
         volume: 1.0f32
       }),
+      speed_of_sound:  DEFAULT_SPEED_OF_SOUND,
+      doppler_enabled: false,
     }))
   }
 
@@ -74,14 +111,17 @@ impl Source
     Arc::new(Mutex::new(Source {
       sample:   0,
       internal: SourceInternal::Source2D(Source2D {
-        origin:        Vec2::ZERO,
-        velocity:      Vec2::ZERO,
-        volume_min:    0.0f32,
-        volume_max:    1.0f32,
-        distance_min:  0.0f32,
-        distance_max:  1.0f32,
-        sample_offset: 0,
+        origin:         Vec2::ZERO,
+        velocity:       Vec2::ZERO,
+        volume_min:     0.0f32,
+        volume_max:     1.0f32,
+        distance_min:   0.0f32,
+        distance_max:   1.0f32,
+        distance_model: DistanceModel::Linear,
+        sample_offset:  0,
       }),
+      speed_of_sound:  DEFAULT_SPEED_OF_SOUND,
+      doppler_enabled: false,
     }))
   }
 
@@ -90,17 +130,34 @@ impl Source
     Arc::new(Mutex::new(Source {
       sample:   0,
       internal: SourceInternal::Source3D(Source3D {
-        origin:        Vec3::ZERO,
-        velocity:      Vec3::ZERO,
-        volume_min:    0.0f32,
-        volume_max:    1.0f32,
-        distance_min:  0.0f32,
-        distance_max:  1.0f32,
-        sample_offset: 0,
+        origin:         Vec3::ZERO,
+        velocity:       Vec3::ZERO,
+        volume_min:     0.0f32,
+        volume_max:     1.0f32,
+        distance_min:   0.0f32,
+        distance_max:   1.0f32,
+        distance_model: DistanceModel::Linear,
+        sample_offset:  0,
       }),
+      speed_of_sound:  DEFAULT_SPEED_OF_SOUND,
+      doppler_enabled: false,
     }))
   }
 
+  pub fn set_speed_of_sound(&mut self, speed_of_sound: f32)
+  {
+    // A non-positive speed of sound makes `doppler_ratio`'s
+    // resampling factor undefined (division by zero or a sign flip).
+    assert!(speed_of_sound > 0.0f32);
+
+    self.speed_of_sound = speed_of_sound;
+  }
+
+  pub fn set_doppler_enabled(&mut self, enabled: bool)
+  {
+    self.doppler_enabled = enabled;
+  }
+
   pub fn set_volume(&mut self, volume: f32)
   {
     // These invariants prevent clipping and potential damage
@@ -147,10 +204,16 @@ impl Source
 
     match &mut self.internal {
       SourceInternal::Source2D(internal) => {
+        // `Inverse`/`Exponential` divide by `distance_min` (see
+        // `distance_gain`); a zero `distance_min` would silently mute
+        // the source rather than panic, so non-`Linear` models
+        // require it to be strictly positive.
+        assert!(min > 0.0f32 || matches!(internal.distance_model, DistanceModel::Linear));
         internal.distance_min = min;
         internal.distance_max = max;
       }
       SourceInternal::Source3D(internal) => {
+        assert!(min > 0.0f32 || matches!(internal.distance_model, DistanceModel::Linear));
         internal.distance_min = min;
         internal.distance_max = max;
       }
@@ -158,6 +221,25 @@ impl Source
     };
   }
 
+  pub fn set_distance_model(&mut self, model: DistanceModel)
+  {
+    match &mut self.internal {
+      SourceInternal::Source2D(internal) => {
+        // See the matching assert in `set_distance_clamp`: `Inverse`/
+        // `Exponential` divide by `distance_min`, so switching to one
+        // of them requires a `distance_min` that was already set
+        // above zero.
+        assert!(matches!(model, DistanceModel::Linear) || internal.distance_min > 0.0f32);
+        internal.distance_model = model;
+      }
+      SourceInternal::Source3D(internal) => {
+        assert!(matches!(model, DistanceModel::Linear) || internal.distance_min > 0.0f32);
+        internal.distance_model = model;
+      }
+      _ => {}
+    };
+  }
+
   pub fn set_origin_2d(&mut self, origin: Vec2)
   {
     if let SourceInternal::Source2D(internal) = &mut self.internal {
@@ -240,11 +322,37 @@ impl Source
     }
   }
 
+  pub fn advance(&mut self, count: usize)
+  {
+    // `sample` is the running count of interleaved samples a `Source`
+    // has emitted. `AudioMixer` advances this by the channel count once
+    // per output frame so `volume`'s `elapsed` computation stays in lock-step with
+    // the audio device's playback position.
+    self.sample += count;
+  }
+
   pub fn volume(&mut self, sample: usize, channels: usize) -> f32
   {
-    fn linear(r: f32) -> f32
+    // Computes the distance-rolloff gain in `[0.0, 1.0]` for `distance`
+    // (already clamped to `[min, max]`) under `model`. This is only
+    // called once the `distance_min`/`distance_max` early-returns above
+    // have been passed, so `min == max` never reaches here. `Inverse`/
+    // `Exponential` additionally divide by `min` itself; `set_distance_model`
+    // and `set_distance_clamp` require `min > 0.0` whenever one of those
+    // models is in effect, so that division is never by zero either.
+    fn distance_gain(model: &DistanceModel, distance: f32, min: f32, max: f32) -> f32
     {
-      1.0f32 - r.clamp(0.0f32, 1.0f32)
+      match *model {
+        DistanceModel::Linear => {
+          1.0f32 - ((distance - min) / (max - min)).clamp(0.0f32, 1.0f32)
+        }
+        DistanceModel::Inverse { rolloff } => {
+          min / (min + rolloff * (distance - min))
+        }
+        DistanceModel::Exponential { rolloff } => {
+          (distance / min).powf(-rolloff)
+        }
+      }
     }
 
     #[rustfmt::skip]
@@ -253,8 +361,60 @@ impl Source
         internal.volume
       }
 
-      SourceInternal::Source3D(_) => {
-        1.0 // TODO(bnpfeife)
+      SourceInternal::Source3D(internal) => {
+        // Computing the seconds `elapsed` since `sample_offset` allows
+        // the caller to mutate `origin` and `velocity` and have the
+        // source be positioned in an "expected" fashion.
+        let elapsed = (sample - internal.sample_offset) as f32 / (
+          AUDIO_DEVICE_CHANNELS as f32 *
+          AUDIO_DEVICE_FREQUENCY as f32
+        );
+
+        let position = internal.origin + (internal.velocity * elapsed);
+
+        let angle = {
+          // The listener is positioned at the origin, facing `+Z`,
+          // with `+X` to the right. Projecting the source's direction
+          // onto the listener's right-vector produces a pan angle.
+          let pan = if position != Vec3::ZERO {
+            // normalize the dot-product to [0.0, 1.0]
+            ((position.normalize().dot(Vec3::X) + 1.0f32) / 2.0f32)
+              .clamp(0.0f32, 1.0f32)
+          } else {
+            // If the position is a zero-vector, it cannot be normalized into
+            // a unit-vector. This produces an audible artifact when sources
+            // overlap the listener. To mitigate this, if a source overlaps
+            // the listener, NX and PX are played at equal volumes.
+            0.5f32
+          };
+
+          match channels {
+            0 => 1.0f32 - pan, // NX "left" listener
+            _ =>        pan,  // PX "right" listener
+          }
+        };
+
+        let distance = position.length().abs();
+        if distance <= internal.distance_min {
+          return internal.volume_max;
+        }
+        if distance >= internal.distance_max {
+          return internal.volume_min;
+        }
+        (
+          angle * (
+            (internal.volume_max - internal.volume_min) *
+              distance_gain(
+                &internal.distance_model,
+                distance,
+                internal.distance_min,
+                internal.distance_max,
+              )
+          ) + internal.volume_min
+        ).clamp(
+          internal.volume_min,
+          internal.volume_max
+        )
       }
 
       SourceInternal::Source2D(internal) => {
@@ -294,13 +454,11 @@ impl Source
         (
           angle * (
             (internal.volume_max - internal.volume_min) *
-              linear(
-                // The `*_gain` functions require that the distance
-                // is between [0.0, 1.0]. Since this computation is
-                // performed beetween `distance_min/max`, the
-                // result is always between [0.0, 1.0].
-                (         distance     - internal.distance_min) /
-                (internal.distance_max - internal.distance_min)
+              distance_gain(
+                &internal.distance_model,
+                distance,
+                internal.distance_min,
+                internal.distance_max,
               )
           ) + internal.volume_min
         ).clamp(
@@ -310,4 +468,258 @@ impl Source
       }
     }
   }
+
+  pub fn doppler_ratio(&mut self, sample: usize) -> f32
+  {
+    if !self.doppler_enabled {
+      return 1.0f32;
+    }
+
+    let speed_of_sound = self.speed_of_sound;
+
+    #[rustfmt::skip]
+    let radial_velocity = match &self.internal {
+      SourceInternal::Source1D(_) => {
+        // `Source1D` has no position or velocity to derive a radial
+        // component from, so it never experiences a Doppler shift.
+        0.0f32
+      }
+
+      SourceInternal::Source3D(internal) => {
+        let elapsed = (sample - internal.sample_offset) as f32 / (
+          AUDIO_DEVICE_CHANNELS as f32 *
+          AUDIO_DEVICE_FREQUENCY as f32
+        );
+
+        let position = internal.origin + (internal.velocity * elapsed);
+
+        // `dir` points from the source toward the listener at the
+        // origin. As with `volume`'s pan, a source overlapping the
+        // listener cannot be normalized; falling back to a zero-vector
+        // yields a neutral (zero) radial velocity instead of a
+        // divide-by-zero artifact.
+        let dir = if position != Vec3::ZERO { -position.normalize() } else { Vec3::ZERO };
+
+        internal.velocity.dot(dir)
+      }
+
+      SourceInternal::Source2D(internal) => {
+        let elapsed = (sample - internal.sample_offset) as f32 / (
+          AUDIO_DEVICE_CHANNELS as f32 *
+          AUDIO_DEVICE_FREQUENCY as f32
+        );
+
+        let position = internal.origin + (internal.velocity * elapsed);
+
+        // `dir` points from the source toward the listener at the
+        // origin. As with `volume`'s pan, a source overlapping the
+        // listener cannot be normalized; falling back to a zero-vector
+        // yields a neutral (zero) radial velocity instead of a
+        // divide-by-zero artifact.
+        let dir = if position != Vec2::ZERO { -position.normalize() } else { Vec2::ZERO };
+
+        internal.velocity.dot(dir)
+      }
+    };
+
+    // `f = c / (c - v_r)`: a source approaching the listener (positive
+    // `v_r`) is resampled faster than 1.0 (higher perceived pitch); a
+    // receding source is resampled slower. Clamped to avoid runaway
+    // pitch-shifting on fast-moving or numerically degenerate sources.
+    (speed_of_sound / (speed_of_sound - radial_velocity))
+      .clamp(DOPPLER_RATIO_MIN, DOPPLER_RATIO_MAX)
+  }
+}
+
+// A single decoded PCM sample tagged with the `clock` it should be
+// played at. A `Reader`/`ResourceProvider` pushes `AudioFrame`s ahead
+// of playback via `AudioMixer::write_samples`; the mixer only drains
+// frames whose `clock` has been reached, so a provider may decode and
+// queue audio far in advance of the output callback consuming it.
+#[derive(Clone, Copy, Debug)]
+struct AudioFrame
+{
+  clock:  u64,
+  sample: f32,
+}
+
+struct MixerSource
+{
+  source: Arc<Mutex<Source>>,
+  queue:  VecDeque<AudioFrame>,
+
+  // The fractional read position into `queue`'s `clock` space. Doppler
+  // shifting advances this by `Source::doppler_ratio` instead of 1.0
+  // per output sample, so a fast-approaching source reads ahead of
+  // (and a receding source reads behind) real-time.
+  cursor: f64,
+}
+
+/// Mixes a set of `Source`s to a cpal output device. Each output
+/// frame, `AudioMixer` advances every source's `sample` counter,
+/// reads its next queued PCM sample, applies `Source::volume` as a
+/// per-channel gain, and sums the result across sources, clamping to
+/// `[-1.0, 1.0]`, the output device's sample range. Sources with an empty queue
+/// contribute silence rather than blocking the callback, so a
+/// momentary decode stall degrades to a dropout instead of an xrun.
+pub struct AudioMixer
+{
+  sources: HashMap<u64, MixerSource>,
+  next_id: AtomicU64,
+  clock:   u64,
+  stream:  Option<Stream>,
+}
+
+impl AudioMixer
+{
+  pub fn new() -> Arc<Mutex<AudioMixer>>
+  {
+    Arc::new(Mutex::new(AudioMixer {
+      sources: HashMap::new(),
+      next_id: AtomicU64::new(0),
+      clock:   0,
+      stream:  None,
+    }))
+  }
+
+  pub fn add_source(&mut self, source: Arc<Mutex<Source>>) -> u64
+  {
+    // `next_id` is monotonic for the lifetime of the mixer, so a
+    // removed source's `id` is never reused by a later `add_source`.
+    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+    self.sources.insert(id, MixerSource {
+      source,
+      queue:  VecDeque::new(),
+      cursor: 0.0f64,
+    });
+
+    id
+  }
+
+  pub fn remove_source(&mut self, id: u64)
+  {
+    self.sources.remove(&id);
+  }
+
+  pub fn write_samples(&mut self, id: u64, clock: u64, samples: &[f32])
+  {
+    // A provider that has not yet been `add_source`d (or has since
+    // been removed) has nowhere to queue samples; the write is
+    // silently dropped, matching `remove_source`'s fire-and-forget
+    // removal.
+    if let Some(mixer_source) = self.sources.get_mut(&id) {
+      mixer_source.queue.extend(
+        samples
+          .iter()
+          .enumerate()
+          .map(|(i, &sample)| AudioFrame { clock: clock + i as u64, sample }),
+      );
+    }
+  }
+
+  /// Builds and starts the cpal output stream for `mixer`, storing the
+  /// resulting `Stream` so it is kept alive (and stopped) alongside the
+  /// `AudioMixer`. `mixer` is shared with the audio callback, so callers
+  /// continue to drive `add_source`/`remove_source`/`write_samples`
+  /// through the same handle while the device renders it in real-time.
+  pub fn start(mixer: &Arc<Mutex<AudioMixer>>) -> Result<(), &'static str>
+  {
+    let device = cpal::default_host()
+      .default_output_device()
+      .ok_or("no default audio output device")?;
+
+    let config = cpal::StreamConfig {
+      channels:    AUDIO_DEVICE_CHANNELS as u16,
+      sample_rate: cpal::SampleRate(AUDIO_DEVICE_FREQUENCY as u32),
+      buffer_size: cpal::BufferSize::Default,
+    };
+
+    let mixer_callback = mixer.clone();
+    let stream = device
+      .build_output_stream(
+        &config,
+        move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+          Self::render(&mixer_callback, data);
+        },
+        |error| eprintln!("audio output stream error: {error}"),
+        None,
+      )
+      .map_err(|_| "failed to build audio output stream")?;
+
+    stream.play().map_err(|_| "failed to start audio output stream")?;
+
+    mixer.lock().unwrap().stream = Some(stream);
+
+    Ok(())
+  }
+
+  // Renders one cpal callback's worth of interleaved, stereo `data`.
+  // This is a free function (rather than a `&mut self` method) so
+  // `start` can call it from within the 'static cpal callback without
+  // holding the `Mutex` for longer than this single render pass.
+  fn render(mixer: &Arc<Mutex<AudioMixer>>, data: &mut [f32])
+  {
+    let mut mixer = mixer.lock().unwrap();
+    let channels = AUDIO_DEVICE_CHANNELS as usize;
+
+    // Drive the read cursor and `volume`/`doppler_ratio` once per
+    // output *frame*, not once per interleaved element: a frame's
+    // channels all share the same underlying PCM sample, just mixed
+    // at a different per-channel gain, so `cursor`/`source.advance`
+    // must not move until every channel of the frame has read it.
+    for output_frame in data.chunks_mut(channels) {
+      let mut mixed = [0.0f32; AUDIO_DEVICE_CHANNELS as usize];
+
+      for mixer_source in mixer.sources.values_mut() {
+        // Drain any frames the read cursor has fully passed; a
+        // doppler-shifted cursor (ratio > 1.0) can skip samples
+        // entirely, so these were never read.
+        while matches!(
+          mixer_source.queue.front(),
+          Some(frame) if (frame.clock as f64) < mixer_source.cursor.floor()
+        ) {
+          mixer_source.queue.pop_front();
+        }
+
+        // Linearly interpolate between the PCM samples bracketing the
+        // fractional `cursor` so the doppler-shifted read rate doesn't
+        // introduce step artifacts. A buffer underrun (the provider
+        // hasn't decoded far enough ahead) contributes silence instead
+        // of blocking the real-time audio thread.
+        let sample_at = |clock: u64| -> f32 {
+          mixer_source
+            .queue
+            .iter()
+            .find(|frame| frame.clock == clock)
+            .map_or(0.0f32, |frame| frame.sample)
+        };
+        let base = mixer_source.cursor.floor() as u64;
+        let frac = mixer_source.cursor.fract() as f32;
+        let pcm = sample_at(base) + (sample_at(base + 1) - sample_at(base)) * frac;
+
+        let mut source = mixer_source.source.lock().unwrap();
+        // `volume`/`doppler_ratio` are driven by the source's own
+        // `sample` counter, not the mixer's `clock`: a source
+        // can be added to the mixer long after `clock` started
+        // counting, and `sample_offset` is stamped relative to
+        // `sample`, not `clock`.
+        let source_sample = source.sample;
+        let doppler_ratio = source.doppler_ratio(source_sample);
+
+        for (channel, output) in mixed.iter_mut().enumerate() {
+          *output += pcm * source.volume(source_sample, channel);
+        }
+
+        source.advance(channels);
+        mixer_source.cursor += doppler_ratio as f64;
+      }
+
+      for (output, mixed) in output_frame.iter_mut().zip(mixed.iter()) {
+        *output = mixed.clamp(-1.0f32, 1.0f32);
+      }
+    }
+
+    mixer.clock += data.len() as u64;
+  }
 }
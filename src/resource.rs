@@ -1,13 +1,24 @@
 use rayon::ThreadPool;
 use rayon::ThreadPoolBuilder;
 use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 pub enum Resource<T>
 {
   Ready(T),
-  Load,
+
+  /// Not yet ready. Carries the in-progress `T` so a provider whose
+  /// `update` decodes incrementally (see `StreamMethod`) has somewhere
+  /// to stash partial state between calls, since `update` is only
+  /// ever given the `Resource<T>` itself. `progress` is the provider's
+  /// best estimate of the fraction complete, in `[0.0, 1.0]`, so a UI
+  /// can show a loading bar; a provider that can't estimate (e.g. an
+  /// unbounded network stream) may leave it at `0.0` throughout.
+  Load { value: T, progress: f32 },
+
   Fail(&'static str),
 }
 
@@ -24,6 +35,14 @@ pub trait ResourceMethod<T, P: ResourceProvider<T>>
     provider: &Arc<P>,
     location: &str,
   ) -> Arc<Mutex<Resource<T>>>;
+
+  /// Requests cancellation of an in-flight load for `resource`. Methods
+  /// that load synchronously (e.g. `StreamMethod`) have nothing running
+  /// in the background to cancel, so the default implementation is a
+  /// no-op; `AsyncMethod` overrides this to stop its spawned task.
+  fn cancel(&self, _resource: &Arc<Mutex<Resource<T>>>)
+  {
+  }
 }
 
 pub trait ResourceProvider<T>
@@ -90,10 +109,18 @@ impl<R, M: ResourceMethod<R, P>, P: ResourceProvider<R>>
     // Retain resources within `self.resources` with more than one strong reference.
     // If a resource only has strong one reference, then there exist no external
     // `std::sync::Arc`s that refer to this resource. Therefore, the resource
-    // can be released.
-    self
-      .resources
-      .retain(|_, resource| Arc::strong_count(&resource) > 1);
+    // can be released. Before releasing an abandoned resource, `method.cancel`
+    // gives e.g. `AsyncMethod` a chance to stop any in-flight load for it, so
+    // an abandoned load stops consuming thread-pool time instead of running
+    // to completion for nothing.
+    let method = &self.method;
+    self.resources.retain(|_, resource| {
+      let retained = Arc::strong_count(resource) > 1;
+      if !retained {
+        method.cancel(resource);
+      }
+      retained
+    });
   }
 }
 
@@ -125,6 +152,12 @@ impl<R, P: ResourceProvider<R>> ResourceMethod<R, P> for StreamMethod
 pub struct AsyncMethod
 {
   thread_pool: ThreadPool,
+
+  // Keyed by the `Arc<Mutex<Resource<_>>>` pointer of each in-flight
+  // load. `cancel` flips the matching flag; the spawned task polls it
+  // between decode steps and the task itself removes its own entry
+  // once the load finishes, so the map only ever holds in-flight loads.
+  cancellations: Arc<Mutex<HashMap<usize, Arc<AtomicBool>>>>,
 }
 
 impl AsyncMethod
@@ -136,12 +169,13 @@ impl AsyncMethod
       thread_pool: ThreadPoolBuilder::new()
         .num_threads(threads)
         .build()
-        .unwrap()
+        .unwrap(),
+      cancellations: Arc::new(Mutex::new(HashMap::new())),
     }
   }
 }
 
-impl<R: Send + 'static, P: ResourceProvider<R> + Send + Sync + 'static>
+impl<R: Send + Default + 'static, P: ResourceProvider<R> + Send + Sync + 'static>
   ResourceMethod<R, P> for AsyncMethod
 {
   fn acquire(
@@ -150,12 +184,26 @@ impl<R: Send + 'static, P: ResourceProvider<R> + Send + Sync + 'static>
     location: &str,
   ) -> Arc<Mutex<Resource<R>>>
   {
-    let resource = Arc::new(Mutex::new(Resource::Load));
+    // `R::default()` is a placeholder occupying `Load` until the
+    // spawned task below replaces it with the provider's own state;
+    // a provider whose `acquire` returns a complete `Resource::Ready`
+    // overwrites it immediately, while a chunked provider keeps
+    // refining it through repeated `update` calls below.
+    let resource = Arc::new(Mutex::new(Resource::Load {
+      value:    R::default(),
+      progress: 0.0,
+    }));
+    let key = Arc::as_ptr(&resource) as usize;
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    self.cancellations.lock().unwrap().insert(key, cancelled.clone());
+
     {
       // `resource` and `provider` are required within the closure. However, variables
       // cannot be borrowed by closures (only moved). Thus, the `resource` and `provider`
       // containers are cloned, and the clones moved into the closure.
       let (resource, provider) = (resource.clone(), provider.clone());
+      let cancellations = self.cancellations.clone();
 
       // `&str` is not guaranteed to be `'static` and `&str` cannot be safely moved into
       // the closure. Converting `location` to a `String` because `String` can be moved
@@ -167,8 +215,39 @@ impl<R: Send + 'static, P: ResourceProvider<R> + Send + Sync + 'static>
         // will execute without acquiring the `Mutex`. This acquires the `Mutex` for the
         // least possible amount of time.
         *resource.lock().unwrap() = provider.acquire(&location);
+
+        // A provider that cannot finish within a single `acquire` call
+        // (the same chunked providers `StreamMethod` drives) leaves the
+        // resource in `Load`; keep calling `update` until it settles,
+        // bailing out early if the caller cancels.
+        loop {
+          if cancelled.load(Ordering::Relaxed) {
+            *resource.lock().unwrap() = Resource::Fail("load cancelled");
+            break;
+          }
+
+          let mut guard = resource.lock().unwrap();
+          if !matches!(&*guard, Resource::Load { .. }) {
+            break;
+          }
+          provider.update(&mut guard);
+          drop(guard);
+
+          std::thread::yield_now();
+        }
+
+        cancellations.lock().unwrap().remove(&key);
       })
     }
+
     resource
   }
+
+  fn cancel(&self, resource: &Arc<Mutex<Resource<R>>>)
+  {
+    let key = Arc::as_ptr(resource) as usize;
+    if let Some(cancelled) = self.cancellations.lock().unwrap().remove(&key) {
+      cancelled.store(true, Ordering::Relaxed);
+    }
+  }
 }
@@ -0,0 +1,228 @@
+use crate::resource::Resource;
+use crate::resource::ResourceProvider;
+use std::fs::File;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use zip::ZipArchive;
+
+/// A uniform byte-source `ResourceProvider`s read from, regardless of
+/// whether a resource lives on disk, inside a mounted archive, or on a
+/// content server. Archive and network sources are read fully into
+/// memory up-front, so `Reader` can implement plain `std::io::Read`
+/// without borrowing from (or blocking on) the originating archive or
+/// socket for the lifetime of the read.
+pub enum Reader
+{
+  File(File),
+  Archive(Cursor<Vec<u8>>),
+  Network(Cursor<Vec<u8>>),
+}
+
+impl Read for Reader
+{
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize>
+  {
+    match self {
+      Reader::File(file) => file.read(buf),
+      Reader::Archive(cursor) => cursor.read(buf),
+      Reader::Network(cursor) => cursor.read(buf),
+    }
+  }
+}
+
+/// A reversible byte-level transform applied to a `Reader`'s contents
+/// after the bytes are fetched. Starts with a simple keyed XOR pass;
+/// the trait exists so a stronger cipher can slot in later without
+/// touching `ArchiveProvider`/`NetworkProvider`.
+pub trait Cipher
+{
+  fn apply(&self, data: &mut [u8]);
+}
+
+/// XORs each byte against a repeating `key`. Not cryptographically
+/// strong on its own, but sufficient to keep assets from being read by
+/// casually opening the archive/stream in a hex editor.
+pub struct XorCipher
+{
+  pub key: Vec<u8>,
+}
+
+impl Cipher for XorCipher
+{
+  fn apply(&self, data: &mut [u8])
+  {
+    assert!(!self.key.is_empty());
+
+    for (i, byte) in data.iter_mut().enumerate() {
+      *byte ^= self.key[i % self.key.len()];
+    }
+  }
+}
+
+/// Serves resources out of a single mounted pak/zip archive, keyed by
+/// the entry name passed as `location`. Optionally decodes each
+/// entry's bytes through a `Cipher` so a game can ship assets in one
+/// encrypted archive instead of loose files on disk.
+pub struct ArchiveProvider
+{
+  archive: Mutex<ZipArchive<File>>,
+  cipher:  Option<Box<dyn Cipher + Send + Sync>>,
+}
+
+impl ArchiveProvider
+{
+  pub fn new(
+    path: &str,
+    cipher: Option<Box<dyn Cipher + Send + Sync>>,
+  ) -> std::io::Result<ArchiveProvider>
+  {
+    let archive = ZipArchive::new(File::open(path)?)
+      .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+    Ok(ArchiveProvider {
+      archive: Mutex::new(archive),
+      cipher,
+    })
+  }
+
+  fn read(&self, location: &str) -> std::io::Result<Reader>
+  {
+    let mut archive = self.archive.lock().unwrap();
+    let mut entry = archive
+      .by_name(location)
+      .map_err(|error| std::io::Error::new(std::io::ErrorKind::NotFound, error))?;
+
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut bytes)?;
+
+    if let Some(cipher) = &self.cipher {
+      cipher.apply(&mut bytes);
+    }
+
+    Ok(Reader::Archive(Cursor::new(bytes)))
+  }
+}
+
+impl ResourceProvider<Vec<u8>> for ArchiveProvider
+{
+  fn acquire(&self, location: &str) -> Resource<Vec<u8>>
+  {
+    let mut reader = match self.read(location) {
+      Ok(reader) => reader,
+      Err(_) => return Resource::Fail("failed to locate archive entry"),
+    };
+
+    let mut bytes = Vec::new();
+    match reader.read_to_end(&mut bytes) {
+      Ok(_) => Resource::Ready(bytes),
+      Err(_) => Resource::Fail("failed to read archive entry"),
+    }
+  }
+
+  fn update(&self, _resource: &mut Resource<Vec<u8>>)
+  {
+    // `acquire` always returns a complete resource; archive entries
+    // are read to the end up-front, so there is nothing left to stream.
+  }
+}
+
+/// Pulls a byte range for `location` from a content server over a
+/// plain TCP socket. `location` may name either the whole resource
+/// (e.g. `"assets/music.ogg"`) or a sub-range of it by suffixing
+/// `#offset-length` (e.g. `"assets/music.ogg#1024-4096"` requests
+/// `4096` bytes starting at byte `1024`). Optionally decodes the
+/// response through a `Cipher`, mirroring `ArchiveProvider`, so assets
+/// can be served without exposing them to casual packet inspection.
+pub struct NetworkProvider
+{
+  address: String,
+  cipher:  Option<Box<dyn Cipher + Send + Sync>>,
+}
+
+impl NetworkProvider
+{
+  pub fn new(address: &str, cipher: Option<Box<dyn Cipher + Send + Sync>>) -> NetworkProvider
+  {
+    NetworkProvider {
+      address: address.to_string(),
+      cipher,
+    }
+  }
+
+  fn read(&self, location: &str) -> std::io::Result<Reader>
+  {
+    // Splits a trailing `#offset-length` suffix off `location`, if
+    // present, into the path the server should fetch and the range to
+    // request from it. Range-less callers get `None` and the whole
+    // resource, matching a plain path with no suffix.
+    let (path, range) = match location.rsplit_once('#') {
+      Some((path, range)) => match range.split_once('-') {
+        Some((offset, length)) => match (offset.parse::<u64>(), length.parse::<u64>()) {
+          (Ok(offset), Ok(length)) => (path, Some((offset, length))),
+          _ => (location, None),
+        },
+        None => (location, None),
+      },
+      None => (location, None),
+    };
+
+    let mut stream = TcpStream::connect(&self.address)?;
+
+    // A minimal request: `path` terminated by a newline, followed by
+    // the requested range as `offset,length` (or `*` for the whole
+    // resource) terminated by a newline. The content server is
+    // expected to reply with exactly the requested bytes and close
+    // the connection.
+    stream.write_all(path.as_bytes())?;
+    stream.write_all(b"\n")?;
+    match range {
+      Some((offset, length)) => stream.write_all(format!("{offset},{length}\n").as_bytes())?,
+      None => stream.write_all(b"*\n")?,
+    }
+
+    let mut bytes = Vec::new();
+    match range {
+      // The server is trusted to honor `length`; capping the read here
+      // as well guards against a misbehaving server sending more than
+      // requested and growing `bytes` unbounded.
+      Some((_, length)) => {
+        stream.take(length).read_to_end(&mut bytes)?;
+      }
+      None => {
+        stream.read_to_end(&mut bytes)?;
+      }
+    }
+
+    if let Some(cipher) = &self.cipher {
+      cipher.apply(&mut bytes);
+    }
+
+    Ok(Reader::Network(Cursor::new(bytes)))
+  }
+}
+
+impl ResourceProvider<Vec<u8>> for NetworkProvider
+{
+  fn acquire(&self, location: &str) -> Resource<Vec<u8>>
+  {
+    let mut reader = match self.read(location) {
+      Ok(reader) => reader,
+      Err(_) => return Resource::Fail("failed to fetch network resource"),
+    };
+
+    let mut bytes = Vec::new();
+    match reader.read_to_end(&mut bytes) {
+      Ok(_) => Resource::Ready(bytes),
+      Err(_) => Resource::Fail("failed to read network resource"),
+    }
+  }
+
+  fn update(&self, _resource: &mut Resource<Vec<u8>>)
+  {
+    // `acquire` always returns a complete resource; the full response
+    // is read up-front, so there is nothing left to stream.
+  }
+}